@@ -8,9 +8,13 @@ use std::{
 use anyhow::Context;
 use cargo_metadata::Message;
 use clap::{builder::PossibleValue, Parser};
+use prometheus_client::{encoding::EncodeLabelSet, metrics::family::Family};
 
 use crate::gitlab::CodeQualityReportEntry;
 
+mod normalize;
+mod terminal;
+
 #[derive(clap::Parser)]
 #[command(version, about, arg_required_else_help = true)]
 struct Args {
@@ -25,6 +29,7 @@ struct Args {
 enum Format {
     Json,
     OpenMetrics,
+    Pretty,
 }
 impl clap::ValueEnum for Format {
     fn value_variants<'a>() -> &'a [Self] {
@@ -36,6 +41,7 @@ impl clap::ValueEnum for Format {
         Some(match self {
             Format::OpenMetrics => PossibleValue::new("open-metrics"),
             Format::Json => PossibleValue::new("json"),
+            Format::Pretty => PossibleValue::new("pretty"),
         })
     }
 }
@@ -50,6 +56,15 @@ struct SubcommandArgs {
     /// use - for stdout
     #[arg(short, long)]
     output: String,
+
+    #[arg(short, long, default_value = "json")]
+    format: Format,
+
+    /// Workspace root to strip from reported paths.
+    ///
+    /// Defaults to the workspace root auto-detected from `cargo metadata`.
+    #[arg(long)]
+    strip_prefix: Option<PathBuf>,
 }
 type RustfmtArgs = SubcommandArgs;
 
@@ -65,6 +80,54 @@ struct LintsArgs {
     /// use - for stdout
     #[arg(short, long)]
     output: String,
+
+    #[arg(short, long, default_value = "json")]
+    format: Format,
+
+    /// Workspace root to strip from reported paths.
+    ///
+    /// Defaults to the workspace root auto-detected from `cargo metadata`.
+    #[arg(long)]
+    strip_prefix: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+#[command(arg_required_else_help = true)]
+struct MergeArgs {
+    /// Report to merge in; use - for stdin. May be passed multiple times.
+    #[arg(short, long = "input", required = true)]
+    inputs: Vec<String>,
+
+    /// use - for stdout
+    #[arg(short, long, default_value = "-")]
+    output: String,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize, strum::Display,
+    strum::VariantArray,
+)]
+#[serde(rename_all = "kebab-case")]
+enum Metric {
+    Packages,
+    DuplicateDependencies,
+    WorkspaceMembers,
+    BinarySize,
+}
+impl clap::ValueEnum for Metric {
+    fn value_variants<'a>() -> &'a [Self] {
+        use strum::VariantArray;
+        Self::VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            Metric::Packages => PossibleValue::new("packages"),
+            Metric::DuplicateDependencies => PossibleValue::new("duplicate-dependencies"),
+            Metric::WorkspaceMembers => PossibleValue::new("workspace-members"),
+            Metric::BinarySize => PossibleValue::new("binary-size"),
+        })
+    }
 }
 
 #[derive(Debug, clap::Args)]
@@ -76,6 +139,16 @@ struct StatsArgs {
     #[arg(short, long, default_value = "json")]
     format: Format,
 
+    /// Which metrics to compute; defaults to all of them except binary-size,
+    /// which requires a full `cargo build` and must be requested explicitly.
+    /// May be passed multiple times.
+    #[arg(long = "metric")]
+    metrics: Vec<Metric>,
+
+    /// Build profile to measure binary sizes for (only used by the binary-size metric)
+    #[arg(long, default_value = "dev")]
+    profile: String,
+
     /// use - for stdout
     #[arg(short, long, default_value = "-")]
     output: String,
@@ -89,6 +162,9 @@ enum Command {
     // Convert rustfmt json output (nightly) to gitlab code quality report
     Rustfmt(RustfmtArgs),
 
+    /// Combine multiple code quality reports into one, deduplicating by fingerprint
+    Merge(MergeArgs),
+
     /// Print out project statistics
     Stats(StatsArgs),
 }
@@ -119,59 +195,259 @@ fn get_outfile(output_filename: &Path) -> Box<dyn Write> {
     }
 }
 
-fn gitlab_clippy(_args: &LintsArgs, input: impl BufRead, output: impl Write) -> io::Result<()> {
-    let result: Vec<CodeQualityReportEntry> = Message::parse_stream(input)
+fn gitlab_clippy(args: &LintsArgs, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut result: Vec<CodeQualityReportEntry> = Message::parse_stream(input)
         .filter_map(Result::ok)
         .filter_map(|each| match each {
             Message::CompilerMessage(msg) => Some(msg.try_into().ok()?),
             _ => None,
         })
         .collect();
-    serde_json::to_writer_pretty(output, &result)?;
+
+    let workspace_root = args
+        .strip_prefix
+        .clone()
+        .unwrap_or_else(normalize::detect_workspace_root);
+    for entry in &mut result {
+        entry.location.path = normalize::path(&entry.location.path, &workspace_root);
+    }
+    gitlab::assign_fingerprints(&mut result);
+
+    match args.format {
+        Format::Json => serde_json::to_writer_pretty(output, &result)?,
+        Format::Pretty => terminal::print_report(&result, &mut output)?,
+        Format::OpenMetrics => {
+            return Err(io::Error::other(
+                "open-metrics output is not supported for lints",
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+fn merge(args: &MergeArgs, mut out: impl Write) -> io::Result<()> {
+    let mut by_fingerprint: std::collections::HashMap<String, CodeQualityReportEntry> =
+        std::collections::HashMap::new();
+
+    for input_filename in &args.inputs {
+        let input = get_infile(input_filename.as_ref());
+        let entries: Vec<CodeQualityReportEntry> = serde_json::from_reader(input)?;
+        for entry in entries {
+            by_fingerprint
+                .entry(entry.fingerprint.clone())
+                .and_modify(|existing| {
+                    if entry.severity > existing.severity {
+                        *existing = entry.clone();
+                    }
+                })
+                .or_insert(entry);
+        }
+    }
+
+    let mut merged: Vec<CodeQualityReportEntry> = by_fingerprint.into_values().collect();
+    // `fingerprint` breaks ties deterministically: HashMap iteration order is
+    // randomized per process, and sort_by is merely stable, so without it
+    // entries tied on (path, lines.begin, severity) would shuffle between runs.
+    merged.sort_by(|a, b| {
+        (
+            &a.location.path,
+            a.location.lines.begin,
+            a.severity,
+            &a.fingerprint,
+        )
+            .cmp(&(
+                &b.location.path,
+                b.location.lines.begin,
+                b.severity,
+                &b.fingerprint,
+            ))
+    });
+
+    serde_json::to_writer_pretty(&mut out, &merged)?;
+    writeln!(&mut out)?;
 
     Ok(())
 }
 
 // ideas:
 //
-// build all targets
-// record binary size of each target
-//
 // memory usage in some releae tests
 //
 // llvm lines for certain functions
 
-#[derive(Clone, Debug, serde::Serialize)]
+#[derive(Clone, Debug, Default, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 struct Stats {
     number_of_packages: Option<usize>,
+    duplicate_dependency_count: Option<usize>,
+    workspace_member_count: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    binary_sizes: Vec<BinarySize>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BinarySize {
+    target: String,
+    profile: String,
+    bytes: u64,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TargetProfileLabels {
+    target: String,
+    profile: String,
+}
+
+/// Runs `cargo build --message-format=json` for `profile` and measures the
+/// on-disk size of each resulting executable artifact. Libraries and other
+/// artifacts without an executable are not measured.
+fn binary_sizes(profile: &str) -> io::Result<Vec<BinarySize>> {
+    let mut child = std::process::Command::new("cargo")
+        .args(["build", "--profile", profile, "--message-format=json"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::other("failed to capture cargo build stdout"))?;
+
+    let sizes = Message::parse_stream(BufReader::new(stdout))
+        .filter_map(Result::ok)
+        .filter_map(|message| match message {
+            Message::CompilerArtifact(artifact) => {
+                let executable = artifact.executable?;
+                let bytes = std::fs::metadata(executable).map(|m| m.len()).unwrap_or(0);
+                Some(BinarySize {
+                    target: artifact.target.name,
+                    profile: profile.to_string(),
+                    bytes,
+                })
+            }
+            _ => None,
+        })
+        .collect();
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "cargo build --profile {profile} failed with {status}"
+        )));
+    }
+
+    Ok(sizes)
 }
 
 fn stats(args: &StatsArgs, mut out: impl Write) -> std::io::Result<()> {
+    // binary-size shells out to a full `cargo build`, so unlike the other
+    // metrics it's opt-in only: it shouldn't turn a cheap `stats` call into a
+    // slow, side-effecting one just because `--metric` was omitted.
+    let explicit_metrics = !args.metrics.is_empty();
+    let wants = |metric: Metric| {
+        if explicit_metrics {
+            args.metrics.contains(&metric)
+        } else {
+            metric != Metric::BinarySize
+        }
+    };
+
     let lockfile = cargo_lock::Lockfile::load(&args.lockfile)
         .context("unable to load lockfile")
         .unwrap();
-    let num_packages = lockfile.packages.len();
+
+    let number_of_packages = wants(Metric::Packages).then_some(lockfile.packages.len());
+
+    let duplicate_dependency_count = wants(Metric::DuplicateDependencies).then(|| {
+        let mut versions_by_name: std::collections::HashMap<&str, std::collections::HashSet<_>> =
+            std::collections::HashMap::new();
+        for package in &lockfile.packages {
+            versions_by_name
+                .entry(package.name.as_str())
+                .or_default()
+                .insert(&package.version);
+        }
+        versions_by_name
+            .values()
+            .filter(|versions| versions.len() > 1)
+            .count()
+    });
+
+    let workspace_member_count = wants(Metric::WorkspaceMembers).then(|| {
+        cargo_metadata::MetadataCommand::new()
+            .exec()
+            .map(|metadata| metadata.workspace_members.len())
+            .unwrap_or(0)
+    });
+
+    let binary_sizes = if wants(Metric::BinarySize) {
+        binary_sizes(&args.profile)?
+    } else {
+        Vec::new()
+    };
 
     match args.format {
         Format::Json => {
             let stats = Stats {
-                number_of_packages: Some(num_packages),
+                number_of_packages,
+                duplicate_dependency_count,
+                workspace_member_count,
+                binary_sizes,
             };
             serde_json::to_writer_pretty(&mut out, &stats)?;
             writeln!(&mut out)?;
         }
         Format::OpenMetrics => {
             let mut registry = prometheus_client::registry::Registry::default();
-            let guage = prometheus_client::metrics::gauge::Gauge::<i64>::default();
-            guage.set(num_packages as i64);
-            registry.register("dependencies", "number of dependencies", guage);
+
+            if let Some(count) = number_of_packages {
+                let gauge = prometheus_client::metrics::gauge::Gauge::<i64>::default();
+                gauge.set(count as i64);
+                registry.register("dependencies", "number of dependencies", gauge);
+            }
+
+            if let Some(count) = duplicate_dependency_count {
+                let gauge = prometheus_client::metrics::gauge::Gauge::<i64>::default();
+                gauge.set(count as i64);
+                registry.register(
+                    "duplicate_dependencies",
+                    "number of packages present at more than one version in the lockfile",
+                    gauge,
+                );
+            }
+
+            if let Some(count) = workspace_member_count {
+                let gauge = prometheus_client::metrics::gauge::Gauge::<i64>::default();
+                gauge.set(count as i64);
+                registry.register("workspace_members", "number of workspace members", gauge);
+            }
+
+            if !binary_sizes.is_empty() {
+                let family = Family::<TargetProfileLabels, prometheus_client::metrics::gauge::Gauge>::default();
+                for binary_size in &binary_sizes {
+                    family
+                        .get_or_create(&TargetProfileLabels {
+                            target: binary_size.target.clone(),
+                            profile: binary_size.profile.clone(),
+                        })
+                        .set(binary_size.bytes as i64);
+                }
+                registry.register(
+                    "binary_size_bytes",
+                    "on-disk size of each executable build artifact",
+                    family,
+                );
+            }
 
             let mut s = String::new();
             prometheus_client::encoding::text::encode(&mut s, &registry)
                 .map_err(io::Error::other)?;
             write!(&mut out, "{}", s)?;
         }
+        Format::Pretty => {
+            return Err(io::Error::other("pretty output is not supported for stats"))
+        }
     }
 
     Ok(())
@@ -191,6 +467,10 @@ fn main() {
             let output = get_outfile(args.output.as_ref());
             rustfmt::rustfmt(&args, input, output).unwrap()
         }
+        Command::Merge(args) => {
+            let output = get_outfile(args.output.as_ref());
+            merge(&args, output).unwrap();
+        }
         Command::Stats(args) => {
             let output = get_outfile(args.output.as_ref());
             stats(&args, output).unwrap();
@@ -200,7 +480,7 @@ fn main() {
 
 mod gitlab {
 
-    use std::hash::Hasher;
+    use std::{collections::HashMap, hash::Hasher};
 
     use cargo_metadata::{diagnostic::DiagnosticLevel, CompilerMessage};
     use serde::{Deserialize, Serialize};
@@ -208,44 +488,91 @@ mod gitlab {
     /// <https://docs.gitlab.com/ee/ci/testing/code_quality.html#implement-a-custom-tool>
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub struct CodeQualityReportEntry {
-        description: String,
-        check_name: String,
-        fingerprint: String,
-        severity: Severity,
-        location: Location,
+        pub(crate) description: String,
+        pub(crate) check_name: String,
+        pub(crate) fingerprint: String,
+        pub(crate) severity: Severity,
+        pub(crate) location: Location,
+        /// The offending source token, used to key the fingerprint. Not part
+        /// of the GitLab schema.
+        #[serde(skip)]
+        snippet: String,
     }
 
     impl CodeQualityReportEntry {
-        pub fn new(
+        pub(crate) fn new(
             check_name: String,
             severity: Severity,
             description: String,
             filename: String,
-            line_number: usize,
+            lines: Lines,
+            positions: Option<Positions>,
+            snippet: String,
         ) -> Self {
-            let fingerprint = {
-                #[allow(deprecated)]
-                let mut hasher = std::hash::SipHasher::new();
-                hasher.write(filename.as_bytes());
-                hasher.write_u8(0xff);
-                hasher.write(description.as_bytes());
-                format!("{:x}", hasher.finish())
-            };
-
             Self {
                 description,
                 check_name,
-                fingerprint,
+                // Assigned later, once the whole batch is known, by `assign_fingerprints`.
+                fingerprint: String::new(),
                 severity,
                 location: Location {
                     path: filename,
-                    lines: Lines { begin: line_number },
+                    lines,
+                    positions,
                 },
+                snippet,
             }
         }
     }
 
-    #[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+    /// Assigns stable, move-resistant fingerprints to a batch of entries.
+    ///
+    /// Hashing the full rendered `description` (as the original implementation
+    /// did) rotates the fingerprint on every wording change or line edit, and
+    /// collides two distinct findings that happen to render the same message.
+    /// Instead this keys on `check_name` + path + a digest of just the
+    /// offending snippet, plus an occurrence index so repeats of the same
+    /// lint on the same snippet within a file don't collide with each other.
+    ///
+    /// Occurrence indices are assigned in `(path, lines.begin)` order rather
+    /// than input order, since input order merely reflects whatever sequence
+    /// rustc/clippy happened to emit diagnostics in, which isn't guaranteed
+    /// stable across runs.
+    pub fn assign_fingerprints(entries: &mut [CodeQualityReportEntry]) {
+        let mut order: Vec<usize> = (0..entries.len()).collect();
+        order.sort_by(|&a, &b| {
+            (&entries[a].location.path, entries[a].location.lines.begin)
+                .cmp(&(&entries[b].location.path, entries[b].location.lines.begin))
+        });
+
+        let mut occurrences: HashMap<(String, String, String), usize> = HashMap::new();
+
+        for index in order {
+            let entry = &entries[index];
+            let key = (
+                entry.check_name.clone(),
+                entry.location.path.clone(),
+                entry.snippet.clone(),
+            );
+            let occurrence = occurrences.entry(key).or_insert(0);
+
+            #[allow(deprecated)]
+            let mut hasher = std::hash::SipHasher::new();
+            hasher.write(entry.check_name.as_bytes());
+            hasher.write_u8(0xff);
+            hasher.write(entry.location.path.as_bytes());
+            hasher.write_u8(0xff);
+            hasher.write(entry.snippet.as_bytes());
+            hasher.write_u8(0xff);
+            hasher.write(&occurrence.to_le_bytes());
+            let fingerprint = format!("{:x}", hasher.finish());
+            *occurrence += 1;
+
+            entries[index].fingerprint = fingerprint;
+        }
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
     #[serde(rename_all = "lowercase")]
     pub enum Severity {
         Info,
@@ -256,14 +583,31 @@ mod gitlab {
     }
 
     #[derive(Clone, Debug, Deserialize, Serialize)]
-    struct Location {
-        path: String,
-        lines: Lines,
+    pub(crate) struct Location {
+        pub(crate) path: String,
+        pub(crate) lines: Lines,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) positions: Option<Positions>,
     }
 
     #[derive(Clone, Debug, Deserialize, Serialize)]
-    struct Lines {
-        begin: usize,
+    pub(crate) struct Lines {
+        pub(crate) begin: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) end: Option<usize>,
+    }
+
+    /// Precise begin/end line+column span, for GitLab's multi-line highlighting.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub(crate) struct Positions {
+        pub(crate) begin: Position,
+        pub(crate) end: Position,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub(crate) struct Position {
+        pub(crate) line: usize,
+        pub(crate) column: usize,
     }
 
     impl TryFrom<CompilerMessage> for CodeQualityReportEntry {
@@ -275,7 +619,6 @@ mod gitlab {
 
             let span = diagnostic.spans.first().ok_or(())?.to_owned();
             let path = span.file_name;
-            let begin = span.line_start;
             let span_text = span
                 .text
                 .iter()
@@ -290,7 +633,21 @@ mod gitlab {
                 diagnostic.level.try_into()?,
                 format!("{description}. {span_text}"),
                 path,
-                begin,
+                Lines {
+                    begin: span.line_start,
+                    end: Some(span.line_end),
+                },
+                Some(Positions {
+                    begin: Position {
+                        line: span.line_start,
+                        column: span.column_start,
+                    },
+                    end: Position {
+                        line: span.line_end,
+                        column: span.column_end,
+                    },
+                }),
+                span_text,
             ))
         }
     }
@@ -317,7 +674,7 @@ mod rustfmt {
     use serde::Deserialize;
 
     use super::*;
-    use crate::gitlab::{CodeQualityReportEntry, Severity};
+    use crate::gitlab::{CodeQualityReportEntry, Lines, Position, Positions, Severity};
 
     #[derive(Clone, Debug, Deserialize)]
     pub struct RustfmtJsonEntry<'a> {
@@ -329,7 +686,7 @@ mod rustfmt {
     #[derive(Clone, Debug, Deserialize)]
     pub struct Mismatch<'a> {
         original_begin_line: usize,
-        // original_end_line: usize,
+        original_end_line: usize,
         // expected_begin_line: usize,
         // expected_end_line: usize,
         original: Cow<'a, str>,
@@ -338,7 +695,10 @@ mod rustfmt {
 
     impl From<RustfmtJsonEntry<'_>> for Vec<CodeQualityReportEntry> {
         fn from(value: RustfmtJsonEntry) -> Self {
-            fn diff(original: &str, expected: &str) -> String {
+            /// Returns the 0-based character offset of the first differing
+            /// character within `original` (a column only when the difference
+            /// lies on its first line), along with the rendered description.
+            fn diff(original: &str, expected: &str) -> (usize, String) {
                 let mut byte_idx = None;
                 for (i, (c1, c2)) in std::iter::zip(original.chars(), expected.chars()).enumerate()
                 {
@@ -347,10 +707,13 @@ mod rustfmt {
                         break;
                     }
                 }
+                let byte_idx = byte_idx.unwrap();
 
-                format!(
-                    "Difference at byte: {}.\noriginal: {original}. expected: {expected}",
-                    byte_idx.unwrap()
+                (
+                    byte_idx,
+                    format!(
+                        "Difference at character: {byte_idx}.\noriginal: {original}. expected: {expected}",
+                    ),
                 )
             }
 
@@ -358,21 +721,40 @@ mod rustfmt {
                 .mismatches
                 .into_iter()
                 .map(|e| {
-                    let description = diff(&e.original, &e.expected);
+                    let (byte_idx, description) = diff(&e.original, &e.expected);
+                    let column = byte_idx + 1;
                     CodeQualityReportEntry::new(
                         "rustfmt".to_string(),
                         Severity::Minor,
                         description,
                         value.name.to_string(),
-                        e.original_begin_line,
+                        Lines {
+                            begin: e.original_begin_line,
+                            end: Some(e.original_end_line),
+                        },
+                        Some(Positions {
+                            begin: Position {
+                                line: e.original_begin_line,
+                                column,
+                            },
+                            end: Position {
+                                line: e.original_end_line,
+                                column,
+                            },
+                        }),
+                        e.original.trim().to_string(),
                     )
                 })
                 .collect()
         }
     }
 
-    pub fn rustfmt(_args: &RustfmtArgs, input: impl BufRead, output: impl Write) -> io::Result<()> {
-        let result: Vec<_> = Message::parse_stream(input)
+    pub fn rustfmt(
+        args: &RustfmtArgs,
+        input: impl BufRead,
+        mut output: impl Write,
+    ) -> io::Result<()> {
+        let mut result: Vec<_> = Message::parse_stream(input)
             .filter_map(Result::ok)
             .flat_map(|each| match each {
                 Message::TextLine(text) => {
@@ -383,7 +765,24 @@ mod rustfmt {
             .flat_map(Vec::<CodeQualityReportEntry>::from)
             .collect();
 
-        serde_json::to_writer_pretty(output, &result)?;
+        let workspace_root = args
+            .strip_prefix
+            .clone()
+            .unwrap_or_else(crate::normalize::detect_workspace_root);
+        for entry in &mut result {
+            entry.location.path = crate::normalize::path(&entry.location.path, &workspace_root);
+        }
+        crate::gitlab::assign_fingerprints(&mut result);
+
+        match args.format {
+            Format::Json => serde_json::to_writer_pretty(output, &result)?,
+            Format::Pretty => crate::terminal::print_report(&result, &mut output)?,
+            Format::OpenMetrics => {
+                return Err(io::Error::other(
+                    "open-metrics output is not supported for rustfmt",
+                ))
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use cargo_metadata::MetadataCommand;
+
+/// Rewrites absolute, machine-specific paths into stable, repo-relative ones.
+///
+/// Mirrors trybuild's path normalization: strip the workspace root so that
+/// reports read the same on every machine and in CI, replace the
+/// `$CARGO_HOME/registry` prefix (which differs per user) with a stable
+/// `<registry>/...` placeholder while keeping the rest of the path (crate
+/// versions and hashes included), and normalize path separators.
+pub fn path(path: &str, workspace_root: &Path) -> String {
+    let path = path.replace('\\', "/");
+    let path_buf = PathBuf::from(&path);
+
+    if let Ok(relative) = path_buf.strip_prefix(workspace_root) {
+        return relative.to_string_lossy().replace('\\', "/");
+    }
+
+    if let Some(registry_root) = cargo_home_registry() {
+        if let Ok(relative) = path_buf.strip_prefix(&registry_root) {
+            return format!(
+                "<registry>/{}",
+                relative.to_string_lossy().replace('\\', "/")
+            );
+        }
+    }
+
+    path
+}
+
+fn cargo_home_registry() -> Option<PathBuf> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))?;
+    Some(cargo_home.join("registry"))
+}
+
+/// Auto-detects the workspace root via `cargo metadata`, falling back to the
+/// current directory if that fails (e.g. outside a cargo project).
+pub fn detect_workspace_root() -> PathBuf {
+    MetadataCommand::new()
+        .exec()
+        .map(|metadata| metadata.workspace_root.into_std_path_buf())
+        .unwrap_or_else(|_| std::env::current_dir().unwrap_or_default())
+}
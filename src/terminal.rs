@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+};
+
+use crate::gitlab::{CodeQualityReportEntry, Severity};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+
+const SEVERITIES_DESCENDING: [Severity; 5] = [
+    Severity::Blocker,
+    Severity::Critical,
+    Severity::Major,
+    Severity::Minor,
+    Severity::Info,
+];
+
+fn color_for(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Blocker | Severity::Critical | Severity::Major => RED,
+        Severity::Minor => YELLOW,
+        Severity::Info => BLUE,
+    }
+}
+
+/// Render a report as colored terminal diagnostics with source snippets,
+/// for local use instead of only viewing reports inside the GitLab MR widget.
+pub fn print_report(entries: &[CodeQualityReportEntry], mut out: impl Write) -> io::Result<()> {
+    let mut source_cache: HashMap<String, Option<Vec<String>>> = HashMap::new();
+    let mut counts: HashMap<Severity, usize> = HashMap::new();
+
+    for entry in entries {
+        *counts.entry(entry.severity).or_default() += 1;
+        let color = color_for(entry.severity);
+
+        writeln!(
+            out,
+            "{BOLD}{color}{:?}{RESET}: {} {DIM}[{}]{RESET}",
+            entry.severity, entry.description, entry.check_name,
+        )?;
+
+        let path = &entry.location.path;
+        let begin_line = entry.location.lines.begin;
+        let end_line = entry
+            .location
+            .positions
+            .as_ref()
+            .map(|positions| positions.end.line)
+            .or(entry.location.lines.end)
+            .filter(|end| *end >= begin_line)
+            .unwrap_or(begin_line);
+        let line_display = if end_line != begin_line {
+            format!("{begin_line}-{end_line}")
+        } else {
+            begin_line.to_string()
+        };
+        writeln!(out, "  {DIM}-->{RESET} {path}:{line_display}")?;
+
+        let lines = source_cache.entry(path.clone()).or_insert_with(|| {
+            fs::read_to_string(path)
+                .ok()
+                .map(|contents| contents.lines().map(str::to_owned).collect())
+        });
+
+        let gutter_width = end_line.to_string().len();
+        writeln!(out, "{DIM}{:gutter_width$} |{RESET}", "")?;
+
+        for current_line in begin_line..=end_line {
+            let Some(text) = lines
+                .as_ref()
+                .and_then(|lines| current_line.checked_sub(1).and_then(|idx| lines.get(idx)))
+            else {
+                continue;
+            };
+
+            writeln!(out, "{DIM}{current_line:gutter_width$} |{RESET} {text}")?;
+
+            // Prefer the exact clippy/rustfmt span when we have one; otherwise
+            // fall back to underlining the trimmed line.
+            let (underline_start, underline_len) = match &entry.location.positions {
+                Some(positions) => {
+                    let start = if current_line == positions.begin.line {
+                        positions.begin.column.saturating_sub(1)
+                    } else {
+                        0
+                    };
+                    let end_column = if current_line == positions.end.line {
+                        positions.end.column
+                    } else {
+                        text.len() + 1
+                    };
+                    (start, end_column.saturating_sub(start + 1).max(1))
+                }
+                None => (
+                    text.len() - text.trim_start().len(),
+                    text.trim().len().max(1),
+                ),
+            };
+            writeln!(
+                out,
+                "{DIM}{:gutter_width$} |{RESET} {}{color}{}{RESET}",
+                "",
+                " ".repeat(underline_start),
+                "^".repeat(underline_len),
+            )?;
+        }
+        writeln!(out)?;
+    }
+
+    write!(out, "{BOLD}summary:{RESET}")?;
+    for severity in SEVERITIES_DESCENDING {
+        if let Some(count) = counts.get(&severity) {
+            write!(out, " {}{:?}{}: {count}", color_for(severity), severity, RESET)?;
+        }
+    }
+    writeln!(out)?;
+
+    Ok(())
+}